@@ -1,20 +1,51 @@
-use cosigning_server::{config::Config, cosignerd::CosignerD};
+use cosigning_server::{
+    config::Config,
+    cosignerd::{rotate_noise_key, CosignerD},
+    pool::ThreadPool,
+};
 use daemonize_simple::Daemonize;
-use revault_net::{message::cosigner::Sign, noise::PublicKey as NoisePubkey};
-use std::{env, net::TcpListener, path::PathBuf, process, str::FromStr};
+use revault_net::message::cosigner::Sign;
+use std::{
+    env,
+    net::{TcpListener, TcpStream},
+    path::PathBuf,
+    process,
+    sync::Arc,
+    time::Duration,
+};
+
+/// How long a single read or write on a manager connection may block, so a stalled peer is
+/// reaped instead of tying up one of our `max_connections` workers forever. This bounds each
+/// read and write call, not the connection's total duration.
+const MANAGER_TIMEOUT: Duration = Duration::from_secs(30);
+
+enum Command {
+    Run(Option<PathBuf>),
+    Rotate(Option<PathBuf>),
+}
 
-fn parse_args(args: Vec<String>) -> Option<PathBuf> {
-    if args.len() == 1 {
-        return None;
-    }
+fn parse_args(args: Vec<String>) -> Command {
+    let conf_flag_pos = args.iter().position(|a| a == "--conf");
+    let conf_file = conf_flag_pos.map(|i| {
+        PathBuf::from(
+            args.get(i + 1)
+                .unwrap_or_else(|| {
+                    eprintln!("'--conf' expects a configuration file path.");
+                    process::exit(1);
+                })
+                .to_owned(),
+        )
+    });
 
-    if args.len() != 3 {
-        eprintln!("Unknown arguments '{:?}'.", args);
-        eprintln!("Only '--conf <configuration file path>' is supported.");
-        process::exit(1);
+    match args.get(1).map(String::as_str) {
+        None | Some("--conf") => Command::Run(conf_file),
+        Some("rotate") => Command::Rotate(conf_file),
+        Some(a) => {
+            eprintln!("Unknown argument '{}'.", a);
+            eprintln!("Only 'rotate' and '--conf <configuration file path>' are supported.");
+            process::exit(1);
+        }
     }
-
-    Some(PathBuf::from(args[2].to_owned()))
 }
 
 // This creates the log file automagically if it doesn't exist, and logs on stdout
@@ -44,66 +75,141 @@ fn setup_logger(
     Ok(())
 }
 
+// Handle a single manager connection: the Noise handshake, reading and decoding its `Sign`
+// message, processing it, and writing back the `SignatureResult`. Called from a worker thread,
+// so a stalled handshake or read on this connection only ties up its own worker.
+//
+// The handshake is performed directly on the `stream` this worker was handed, not on a fresh
+// connection pulled off the listener: the latter would let every dispatched job silently
+// consume a *different* incoming connection than the one it was given, with several workers
+// racing on `listener.accept()` in the process.
+fn handle_connection(
+    stream: TcpStream,
+    noise_privkeys: &[revault_net::noise::SecretKey],
+    cosignerd: &CosignerD,
+    managers_noise_pubkeys: &[revault_net::noise::PublicKey],
+) {
+    let peer_addr = stream.peer_addr().ok();
+
+    // Bound how long a stalled manager connection can tie up this worker: without this, a
+    // peer that stops sending mid-handshake or never sends its `Sign` message would park a
+    // worker forever, and `max_connections` of them would deny service to every other manager
+    // just as surely as the single-threaded version this pool replaced. The timeouts apply to
+    // each read and write; a peer that keeps sending a little data within each timeout is not
+    // cut off.
+    if let Err(e) = stream.set_read_timeout(Some(MANAGER_TIMEOUT)) {
+        log::error!("Error setting read timeout on '{:?}': '{}'", peer_addr, e);
+        return;
+    }
+    if let Err(e) = stream.set_write_timeout(Some(MANAGER_TIMEOUT)) {
+        log::error!("Error setting write timeout on '{:?}': '{}'", peer_addr, e);
+        return;
+    }
+
+    // Tries our current Noise identity first and, during a key rotation's transition window,
+    // falls back to the keys we rotated away from, all against this single accepted connection:
+    // a manager gets exactly one shot at the handshake, it just isn't required to have picked up
+    // our newest key yet.
+    let mut kk_stream = match revault_net::transport::KKTransport::accept_stream(
+        stream,
+        noise_privkeys,
+        managers_noise_pubkeys,
+    ) {
+        Ok(s) => s,
+        Err(e) => {
+            log::error!("Error during handshake with '{:?}': '{}'", peer_addr, e);
+            return;
+        }
+    };
+    if cosignerd.is_deprecated_manager_key(&kk_stream.remote_static()) {
+        log::warn!(
+            "Manager '{}' is still using a deprecated Noise key",
+            revault_net::sodiumoxide::hex::encode(&kk_stream.remote_static().0)
+        );
+    }
+
+    let buf = match kk_stream.read() {
+        Ok(buf) => buf,
+        Err(e) => {
+            log::error!("Error reading from stream '{:?}': '{}'", peer_addr, e);
+            return;
+        }
+    };
+    log::debug!(
+        "Got '{}' from '{}'",
+        String::from_utf8_lossy(&buf),
+        revault_net::sodiumoxide::hex::encode(&kk_stream.remote_static().0)
+    );
+    let sign_msg: Sign = match serde_json::from_slice(&buf) {
+        Ok(msg) => msg,
+        // FIXME: This should probably be fatal, they are violating the protocol
+        Err(e) => {
+            log::error!("Decoding sign message: '{}'", e);
+            return;
+        }
+    };
+    log::trace!("Decoded: {:#?}", sign_msg);
+
+    let sign_res = match cosignerd.process_sign_message(sign_msg.tx) {
+        Ok(res) => res,
+        Err(e) => {
+            log::error!("Error processing sign message: '{}'", e);
+            return;
+        }
+    };
+    if let Err(e) =
+        kk_stream.write(&serde_json::to_vec(&sign_res).expect("Failed to serialize signature result"))
+    {
+        log::error!("Error writing to stream '{:?}': '{}'", peer_addr, e);
+    }
+}
+
 // Wait for connections from managers on the configured interface and process `sign` messages.
-fn daemon_main(mut cosignerd: CosignerD) {
+// Each connection is dispatched to a bounded worker pool so one stalled manager can't deny
+// service to the others.
+fn daemon_main(cosignerd: CosignerD) {
     let host = cosignerd.listen;
     let listener = TcpListener::bind(host).unwrap_or_else(|e| {
         log::error!("Error binding on '{}': '{}'", host, e);
         process::exit(1);
     });
-    let managers_noise_pubkeys: Vec<NoisePubkey> =
-        cosignerd.managers.iter().map(|m| m.noise_key).collect();
+    let managers_noise_pubkeys = Arc::new(cosignerd.managers_noise_pubkeys());
+    let noise_privkeys = Arc::new(cosignerd.noise_privkeys().cloned().collect::<Vec<_>>());
+    let pool = ThreadPool::new(cosignerd.max_connections);
+    let cosignerd = Arc::new(cosignerd);
 
-    // We expect a single connection once in a while, there is *no need* for complexity here so
-    // just treat incoming connections sequentially.
     for stream in listener.incoming() {
         log::trace!("Got a new connection: '{:?}'", stream);
         let stream = match stream {
             Ok(s) => s,
             Err(e) => continue,
         };
-        // This does the Noise KK handshake.
-        let mut kk_stream = match revault_net::transport::KKTransport::accept(
-            &listener,
-            &cosignerd.noise_privkey,
-            &managers_noise_pubkeys,
-        ) {
-            Ok(s) => s,
-            Err(e) => {
-                log::error!("Error during handshake: '{}'", e);
-                continue;
-            }
-        };
-
-        let buf = match kk_stream.read() {
-            Ok(buf) => buf,
-            Err(e) => {
-                log::error!("Error reading from stream '{:?}': '{}'", stream, e);
-                continue;
-            }
-        };
-        log::debug!(
-            "Got '{}' from '{}'",
-            String::from_utf8_lossy(&buf),
-            revault_net::sodiumoxide::hex::encode(&kk_stream.remote_static().0)
-        );
-        let sign_msg: Sign = match serde_json::from_slice(&buf) {
-            Ok(msg) => msg,
-            // FIXME: This should probably be fatal, they are violating the protocol
-            Err(e) => {
-                log::error!("Decoding sign message: '{}'", e);
-                continue;
-            }
-        };
-        log::trace!("Decoded: {:#?}", sign_msg);
 
-        // TODO: process sign message
+        let cosignerd = Arc::clone(&cosignerd);
+        let managers_noise_pubkeys = Arc::clone(&managers_noise_pubkeys);
+        let noise_privkeys = Arc::clone(&noise_privkeys);
+        pool.execute(move || {
+            handle_connection(stream, &noise_privkeys, &cosignerd, &managers_noise_pubkeys)
+        });
     }
 }
 
 fn main() {
     let args = env::args().collect();
-    let conf_file = parse_args(args);
+    let conf_file = match parse_args(args) {
+        Command::Rotate(conf_file) => {
+            let config = Config::from_file(conf_file).unwrap_or_else(|e| {
+                eprintln!("Error parsing config: {}", e);
+                process::exit(1);
+            });
+            rotate_noise_key(&config.data_dir).unwrap_or_else(|e| {
+                eprintln!("Error rotating Noise key: {}", e);
+                process::exit(1);
+            });
+            return;
+        }
+        Command::Run(conf_file) => conf_file,
+    };
 
     let config = Config::from_file(conf_file).unwrap_or_else(|e| {
         eprintln!("Error parsing config: {}", e);
@@ -112,7 +218,7 @@ fn main() {
     let log_level = config.log_level;
 
     // Construct CosignerD (global state)
-    let mut cosignerd = CosignerD::from_config(config).unwrap_or_else(|e| {
+    let cosignerd = CosignerD::from_config(config).unwrap_or_else(|e| {
         eprintln!("Error creating global state: {}", e);
         process::exit(1);
     });
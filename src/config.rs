@@ -0,0 +1,150 @@
+//! Configuration logic for cosignerd.
+
+use revault_net::noise::PublicKey as NoisePubkey;
+
+use std::{fmt, fs, io, net::SocketAddr, path::PathBuf, str::FromStr};
+
+use serde::{de, Deserialize, Deserializer};
+
+fn deserialize_loglevel<'de, D>(deserializer: D) -> Result<log::LevelFilter, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    log::LevelFilter::from_str(&s)
+        .map_err(|e| de::Error::custom(format!("Invalid log level '{}': '{}'", s, e)))
+}
+
+fn default_loglevel() -> log::LevelFilter {
+    log::LevelFilter::Info
+}
+
+fn default_daemon() -> bool {
+    false
+}
+
+fn default_max_connections() -> usize {
+    8
+}
+
+/// Which backend to use to produce our Bitcoin signatures.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SignerConfig {
+    /// The default: a plain key held in the daemon's own process memory.
+    InProcess,
+}
+
+fn default_signer_config() -> SignerConfig {
+    SignerConfig::InProcess
+}
+
+/// Which backend to use to persist the anti-replay `signed_outpoints` record.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DbConfig {
+    /// The default: a local SQLite file under the data directory.
+    Sqlite,
+    /// A networked, replicated store shared by several cosigner instances.
+    Networked { store_addr: SocketAddr },
+}
+
+fn default_db_config() -> DbConfig {
+    DbConfig::Sqlite
+}
+
+/// One of a manager's static Noise public keys. Keeping a manager's rotated-out keys around
+/// (marked `deprecated`) lets us keep accepting its connections during a transition window
+/// instead of a hard cutover.
+#[derive(Debug, Clone, Deserialize)]
+pub struct NoiseKeyEntry {
+    pub key: NoisePubkey,
+    #[serde(default)]
+    pub deprecated: bool,
+}
+
+/// A manager we accept `Sign` requests from, identified by its static Noise public key(s).
+///
+/// The first non-deprecated entry is the one currently in use; any `deprecated` entries are
+/// still accepted, but their use is logged so operators know when it is safe to remove them.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ManagerConfig {
+    pub noise_keys: Vec<NoiseKeyEntry>,
+}
+
+/// Our static configuration, read from the configuration file at startup.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    /// The managers we accept connections from.
+    pub managers: Vec<ManagerConfig>,
+    /// Where we should store our data (database, keys, logs, ...).
+    pub data_dir: PathBuf,
+    /// The address to listen for managers' connections on.
+    pub listen: SocketAddr,
+    /// What messages to log
+    #[serde(
+        default = "default_loglevel",
+        deserialize_with = "deserialize_loglevel"
+    )]
+    pub log_level: log::LevelFilter,
+    /// Whether to daemonize the process
+    #[serde(default = "default_daemon")]
+    pub daemon: bool,
+    /// Which backend to use to sign spend transaction inputs.
+    #[serde(default = "default_signer_config")]
+    pub signer: SignerConfig,
+    /// How many manager connections we process concurrently at most.
+    #[serde(default = "default_max_connections")]
+    pub max_connections: usize,
+    /// Which backend to use to persist the anti-replay record.
+    #[serde(default = "default_db_config")]
+    pub db: DbConfig,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    FileNotFound,
+    ReadingFile(io::Error),
+    ParsingFile(toml::de::Error),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::FileNotFound => write!(
+                f,
+                "Could not locate the configuration file, please set it explicitly with '--conf <configuration file path>'"
+            ),
+            Self::ReadingFile(e) => write!(f, "Error reading configuration file: '{}'", e),
+            Self::ParsingFile(e) => write!(f, "Error parsing configuration file: '{}'", e),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+fn default_config_path() -> Option<PathBuf> {
+    #[cfg(target_os = "linux")]
+    let configs_dir = dirs::config_dir();
+    #[cfg(not(target_os = "linux"))]
+    let configs_dir = dirs::home_dir();
+
+    configs_dir.map(|mut path| {
+        path.push(".cosignerd");
+        path.push("cosignerd.toml");
+        path
+    })
+}
+
+impl Config {
+    /// Get our configuration out of a TOML file. If no path is given, a default path (platform
+    /// dependant) is used instead.
+    pub fn from_file(custom_path: Option<PathBuf>) -> Result<Config, ConfigError> {
+        let config_file = custom_path
+            .or_else(default_config_path)
+            .ok_or(ConfigError::FileNotFound)?;
+
+        let file_content = fs::read(&config_file).map_err(ConfigError::ReadingFile)?;
+        toml::from_slice::<Config>(&file_content).map_err(ConfigError::ParsingFile)
+    }
+}
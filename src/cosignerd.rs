@@ -0,0 +1,286 @@
+//! The cosigning server's global state and core signing logic.
+
+use crate::{
+    config::{Config, ManagerConfig, SignerConfig},
+    database::{db_from_config, DatabaseError, Db},
+    signer::{InProcessSigner, Signer},
+};
+
+use revault_net::{message::cosigner::SignatureResult, noise::SecretKey as NoisePrivkey};
+use revault_tx::{
+    miniscript::bitcoin::util::bip32,
+    transactions::SpendTransaction,
+};
+
+use std::{
+    fmt, fs, io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+#[derive(Debug)]
+pub enum CosignerDError {
+    Io(io::Error),
+    Database(DatabaseError),
+    /// An unvault output's derivation index can't be derived from our xpub alone, most likely
+    /// because it's a hardened index. This should never happen for a legitimate vault, as vault
+    /// descriptors are always derived from the unhardened wildcard of a manager/stakeholder/
+    /// cosigner xpub, so we treat it as a malformed `Sign` request rather than a bug.
+    InvalidDerivationIndex(bip32::ChildNumber),
+}
+
+impl fmt::Display for CosignerDError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "I/O error: '{}'", e),
+            Self::Database(e) => write!(f, "Database error: '{}'", e),
+            Self::InvalidDerivationIndex(i) => {
+                write!(f, "Cannot derive our public key at index '{}'", i)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CosignerDError {}
+
+const NOISE_KEY_FILE: &str = "noise_secret";
+const DEPRECATED_NOISE_KEYS_FILE: &str = "noise_secret.deprecated";
+const BITCOIN_KEY_FILE: &str = "bitcoin_secret";
+
+// Our private keys are never in the configuration file: they are generated on first startup and
+// stored under the data directory instead, so that they never transit through (and get dumped
+// in a backup of) the config.
+fn read_or_create_noise_key(data_dir: &Path) -> Result<NoisePrivkey, CosignerDError> {
+    let path = data_dir.join(NOISE_KEY_FILE);
+
+    if path.exists() {
+        let bytes = fs::read(&path).map_err(CosignerDError::Io)?;
+        Ok(NoisePrivkey::from_slice(&bytes).expect("Invalid Noise key stored on disk"))
+    } else {
+        let (_, secret_key) = revault_net::sodiumoxide::crypto::box_::gen_keypair();
+        fs::write(&path, &secret_key.0).map_err(CosignerDError::Io)?;
+        Ok(secret_key)
+    }
+}
+
+// Keys we rotated away from, kept around (one per line, hex-encoded) so we can still accept
+// handshakes from managers who haven't picked up our new key yet.
+fn read_deprecated_noise_keys(data_dir: &Path) -> Result<Vec<NoisePrivkey>, CosignerDError> {
+    let path = data_dir.join(DEPRECATED_NOISE_KEYS_FILE);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let content = fs::read_to_string(&path).map_err(CosignerDError::Io)?;
+    Ok(content
+        .lines()
+        .filter(|l| !l.is_empty())
+        .map(|l| {
+            let bytes = revault_net::sodiumoxide::hex::decode(l)
+                .expect("Invalid hex in deprecated Noise keys file");
+            NoisePrivkey::from_slice(&bytes).expect("Invalid deprecated Noise key stored on disk")
+        })
+        .collect())
+}
+
+/// Rotate our own Noise static key: the current key is kept around (marked deprecated) so we
+/// keep accepting handshakes encrypted for it during the transition, and a fresh key becomes our
+/// new identity.
+pub fn rotate_noise_key(data_dir: &Path) -> Result<(), CosignerDError> {
+    let current = read_or_create_noise_key(data_dir)?;
+
+    let deprecated_path = data_dir.join(DEPRECATED_NOISE_KEYS_FILE);
+    let mut deprecated = fs::read_to_string(&deprecated_path).unwrap_or_default();
+    deprecated.push_str(&revault_net::sodiumoxide::hex::encode(&current.0));
+    deprecated.push('\n');
+    fs::write(&deprecated_path, deprecated).map_err(CosignerDError::Io)?;
+
+    let (_, new_key) = revault_net::sodiumoxide::crypto::box_::gen_keypair();
+    fs::write(data_dir.join(NOISE_KEY_FILE), &new_key.0).map_err(CosignerDError::Io)?;
+
+    log::info!("Rotated our Noise static key. The previous key is kept as deprecated.");
+    Ok(())
+}
+
+fn signer_from_config(
+    data_dir: &Path,
+    signer_config: &SignerConfig,
+) -> Result<Box<dyn Signer>, CosignerDError> {
+    match signer_config {
+        SignerConfig::InProcess => {
+            let path = data_dir.join(BITCOIN_KEY_FILE);
+            Ok(Box::new(
+                InProcessSigner::read_or_create(&path).map_err(CosignerDError::Io)?,
+            ))
+        }
+    }
+}
+
+/// The cosigning server's global state.
+#[derive(Debug)]
+pub struct CosignerD {
+    pub managers: Vec<ManagerConfig>,
+    pub listen: SocketAddr,
+    pub noise_privkey: NoisePrivkey,
+    /// Keys we previously used as our Noise identity, still accepted during a rotation's
+    /// transition window.
+    pub deprecated_noise_privkeys: Vec<NoisePrivkey>,
+    pub signer: Box<dyn Signer>,
+    /// How many manager connections to process concurrently at most.
+    pub max_connections: usize,
+    db: Box<dyn Db>,
+    data_dir: PathBuf,
+    /// Guards the anti-replay check-then-insert so it stays atomic across connections handled
+    /// concurrently by this process. `db.try_record_spend` is itself the authoritative,
+    /// backend-enforced guarantee: this lock only avoids redundant signing work when two
+    /// requests for the same outpoint race within this process.
+    db_lock: Mutex<()>,
+}
+
+impl CosignerD {
+    /// Build our global state out of the static configuration, creating the data directory, the
+    /// database, and our long-term keys if this is the first startup.
+    pub fn from_config(config: Config) -> Result<CosignerD, CosignerDError> {
+        let data_dir = config.data_dir;
+        if !data_dir.exists() {
+            fs::create_dir_all(&data_dir).map_err(CosignerDError::Io)?;
+        }
+
+        let db = db_from_config(&data_dir, &config.db).map_err(CosignerDError::Database)?;
+
+        let noise_privkey = read_or_create_noise_key(&data_dir)?;
+        let deprecated_noise_privkeys = read_deprecated_noise_keys(&data_dir)?;
+        let signer = signer_from_config(&data_dir, &config.signer)?;
+
+        Ok(CosignerD {
+            managers: config.managers,
+            listen: config.listen,
+            noise_privkey,
+            deprecated_noise_privkeys,
+            signer,
+            max_connections: config.max_connections,
+            db,
+            data_dir,
+            db_lock: Mutex::new(()),
+        })
+    }
+
+    pub fn log_file(&self) -> PathBuf {
+        self.data_dir.join("log")
+    }
+
+    pub fn pid_file(&self) -> PathBuf {
+        self.data_dir.join("cosignerd.pid")
+    }
+
+    /// All the Noise static pubkeys we accept a manager connection against, across every
+    /// manager and including their deprecated (rotated-out) keys.
+    pub fn managers_noise_pubkeys(&self) -> Vec<revault_net::noise::PublicKey> {
+        self.managers
+            .iter()
+            .flat_map(|m| m.noise_keys.iter())
+            .map(|entry| entry.key)
+            .collect()
+    }
+
+    /// Whether `key` is a manager's deprecated Noise pubkey, so we can log when one is still in
+    /// use and it isn't yet safe for the operator to remove it from the configuration.
+    pub fn is_deprecated_manager_key(&self, key: &revault_net::noise::PublicKey) -> bool {
+        self.managers
+            .iter()
+            .flat_map(|m| m.noise_keys.iter())
+            .any(|entry| &entry.key == key && entry.deprecated)
+    }
+
+    /// Every Noise privkey we should try to accept a handshake with: our current identity, then
+    /// our deprecated ones, in order.
+    pub fn noise_privkeys(&self) -> impl Iterator<Item = &NoisePrivkey> {
+        std::iter::once(&self.noise_privkey).chain(self.deprecated_noise_privkeys.iter())
+    }
+
+    /// Process a `Sign` request from a manager: sign every input of the given spend transaction,
+    /// enforcing that we never emit signatures for two distinct spends sharing an unvault
+    /// outpoint.
+    ///
+    /// Returns a `SignatureResult` with no transaction if we refuse to sign (because of the
+    /// anti-replay check), as we never partially sign a transaction.
+    pub fn process_sign_message(
+        &self,
+        mut spend_tx: SpendTransaction,
+    ) -> Result<SignatureResult, CosignerDError> {
+        let secp = revault_tx::miniscript::bitcoin::secp256k1::Secp256k1::new();
+        let spend_txid = spend_tx.txid();
+
+        // We process connections concurrently, but the anti-replay check-then-insert must stay
+        // atomic: hold a single lock across both so two threads never interleave a check and an
+        // insert for the same outpoint within this process.
+        let _db_guard = self.db_lock.lock().expect("Database lock is poisoned");
+
+        // First pass: make sure none of the outpoints we are asked to sign for was already
+        // signed as part of a *different* spend. If that's the case, refuse to sign at all: we
+        // never emit partial signatures.
+        for unvault_txin in spend_tx.inputs() {
+            let outpoint = unvault_txin.outpoint();
+
+            if let Some(prev) = self
+                .db
+                .signed_outpoint(&outpoint)
+                .map_err(CosignerDError::Database)?
+            {
+                if prev.spend_txid != spend_txid {
+                    log::error!(
+                        "Refusing to sign spend '{}': outpoint '{}' was already signed for spend '{}'",
+                        spend_txid,
+                        outpoint,
+                        prev.spend_txid,
+                    );
+                    return Ok(SignatureResult { tx: None });
+                }
+            }
+        }
+
+        // Second pass: every input passed the anti-replay check, sign them all. Each unvault
+        // output was derived at its own vault's index, so we must derive and sign with the
+        // matching child of our own key rather than a single fixed one.
+        let n_inputs = spend_tx.inputs().len();
+        let mut signed_outpoints = Vec::with_capacity(n_inputs);
+        for i in 0..n_inputs {
+            let unvault_txin = &spend_tx.inputs()[i];
+            let outpoint = unvault_txin.outpoint();
+            let derivation = unvault_txin.unvault_txout().derivation_index();
+
+            let pubkey = self
+                .signer
+                .xpub()
+                .derive_pub(&secp, &[derivation])
+                .map_err(|_| CosignerDError::InvalidDerivationIndex(derivation))?
+                .public_key;
+            let sighash = spend_tx.signature_hash(i, &secp);
+            let signature = self.signer.sign_spend_input(&sighash, derivation);
+
+            spend_tx.add_signature(i, pubkey, signature);
+            signed_outpoints.push((outpoint, signature.serialize_der().to_vec()));
+        }
+
+        // Only now that every input is signed do we persist the anti-replay record, as a single
+        // conditional write: if another request raced us for one of these outpoints between our
+        // check above and now, this still can't let two spends of the same outpoint through.
+        if let Some(conflict) = self
+            .db
+            .try_record_spend(&spend_txid, &signed_outpoints)
+            .map_err(CosignerDError::Database)?
+        {
+            log::error!(
+                "Refusing to sign spend '{}': lost a race to record outpoints already claimed by spend '{}'",
+                spend_txid,
+                conflict.spend_txid,
+            );
+            return Ok(SignatureResult { tx: None });
+        }
+
+        Ok(SignatureResult {
+            tx: Some(spend_tx),
+        })
+    }
+}
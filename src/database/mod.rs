@@ -0,0 +1,87 @@
+//! Persistent storage for cosignerd.
+//!
+//! We only ever need to remember one thing across restarts: which unvault outpoints we already
+//! signed a spend for, and as part of which spend transaction. This is what lets us enforce the
+//! "never sign two different spends of the same unvault outpoint" invariant even across daemon
+//! restarts, and across a fleet of cosigners sharing a replicated store.
+//!
+//! The `Db` trait is the boundary between that invariant and the actual storage: a local SQLite
+//! file by default, or a networked, replicated store for operators who run several cosigners
+//! against the same backend.
+
+pub mod networked;
+pub mod sqlite;
+
+use crate::config::DbConfig;
+
+use revault_tx::miniscript::bitcoin::{consensus::encode, OutPoint, Txid};
+
+use std::{fmt, io, path::Path};
+
+#[derive(Debug)]
+pub enum DatabaseError {
+    Sqlite(rusqlite::Error),
+    Encode(encode::Error),
+    Io(io::Error),
+    Serde(serde_json::Error),
+}
+
+impl fmt::Display for DatabaseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Sqlite(e) => write!(f, "Database error: '{}'", e),
+            Self::Encode(e) => write!(f, "Error encoding/decoding from database: '{}'", e),
+            Self::Io(e) => write!(f, "I/O error talking to the database: '{}'", e),
+            Self::Serde(e) => write!(f, "(De)serializing a database request: '{}'", e),
+        }
+    }
+}
+
+impl std::error::Error for DatabaseError {}
+
+impl From<rusqlite::Error> for DatabaseError {
+    fn from(e: rusqlite::Error) -> Self {
+        Self::Sqlite(e)
+    }
+}
+
+/// The record of a previously emitted signature for a given unvault outpoint.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct SignedOutpoint {
+    pub spend_txid: Txid,
+    pub signature: Vec<u8>,
+}
+
+/// Where we persist which unvault outpoints we already signed a spend for.
+///
+/// Implementors MUST make `try_record_spend` a single atomic, conditional write: it is the only
+/// thing standing between a colluding subset of managers and a double spend of an unvaulted
+/// UTXO, so it has to hold even when several cosigner processes share the same backend.
+pub trait Db: fmt::Debug + Send + Sync {
+    /// Look up whether we already signed a spend of this unvault outpoint, and if so for which
+    /// spend transaction. Used for an early, cheap rejection before we bother signing anything.
+    fn signed_outpoint(&self, outpoint: &OutPoint) -> Result<Option<SignedOutpoint>, DatabaseError>;
+
+    /// Atomically check that none of these outpoints were already recorded for a *different*
+    /// spend and, if so, record them all as signed for `spend_txid`. Returns the conflicting
+    /// record if any outpoint was already claimed by another spend, in which case nothing is
+    /// written.
+    fn try_record_spend(
+        &self,
+        spend_txid: &Txid,
+        signed_outpoints: &[(OutPoint, Vec<u8>)],
+    ) -> Result<Option<SignedOutpoint>, DatabaseError>;
+}
+
+/// Build the configured `Db` backend, creating its schema under `data_dir` if it's the local
+/// SQLite one.
+pub fn db_from_config(data_dir: &Path, config: &DbConfig) -> Result<Box<dyn Db>, DatabaseError> {
+    match config {
+        DbConfig::Sqlite => Ok(Box::new(sqlite::SqliteDb::new(
+            data_dir.join("cosignerd.sqlite3"),
+        )?)),
+        DbConfig::Networked { store_addr } => {
+            Ok(Box::new(networked::NetworkedDb::new(*store_addr)))
+        }
+    }
+}
@@ -0,0 +1,123 @@
+//! A networked `Db` backend for operators who want several cosigner processes to share a single,
+//! replicated anti-replay store instead of each keeping its own local SQLite file (the same kind
+//! of move ns-indexer makes going from a local index to a replicated ScyllaDB cluster).
+//!
+//! We don't pull in a database driver here: we talk a tiny JSON request/response protocol over
+//! TCP to a store service, the same way we already exchange JSON messages with managers. The
+//! store itself is expected to implement `try_record_spend` as a single conditional write (e.g. a
+//! Cassandra/ScyllaDB lightweight transaction on the outpoint's primary key) so the invariant
+//! holds across every cosigner sharing it.
+//!
+//! Wire format: each message, in both directions, is a 4-byte big-endian length prefix followed
+//! by that many bytes of JSON. Plain back-to-back `serde_json::to_writer`/`from_reader` calls
+//! can't work over a long-lived TCP connection: nothing marks where a JSON value ends, so the
+//! peer's `from_reader` (which reads until EOF) blocks past the end of the message. A store
+//! implementation MUST speak this same length-prefixed framing on both the request it reads and
+//! the response it writes back on the same connection.
+
+use crate::database::{DatabaseError, Db, SignedOutpoint};
+
+use revault_tx::miniscript::bitcoin::{OutPoint, Txid};
+
+use std::{
+    convert::TryFrom,
+    io::{Read, Write},
+    net::{SocketAddr, TcpStream},
+    time::Duration,
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// How long we wait on the store for a connection or a response before giving up. A hung store
+/// must not be able to stall `CosignerD::process_sign_message`'s lock indefinitely, which would
+/// deny service to every other manager connection.
+const STORE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug)]
+pub struct NetworkedDb {
+    store_addr: SocketAddr,
+}
+
+impl NetworkedDb {
+    pub fn new(store_addr: SocketAddr) -> NetworkedDb {
+        NetworkedDb { store_addr }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+enum Request {
+    SignedOutpoint {
+        outpoint: OutPoint,
+    },
+    TryRecordSpend {
+        spend_txid: Txid,
+        signed_outpoints: Vec<(OutPoint, Vec<u8>)>,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+struct Response {
+    conflict: Option<SignedOutpoint>,
+}
+
+// Write `value` as a 4-byte big-endian length prefix followed by its JSON encoding, so the peer
+// knows exactly where this message ends without relying on the connection being closed.
+fn write_framed<T: Serialize>(stream: &mut TcpStream, value: &T) -> Result<(), DatabaseError> {
+    let payload = serde_json::to_vec(value).map_err(DatabaseError::Serde)?;
+    let len = u32::try_from(payload.len()).expect("Request/response too large to frame");
+
+    stream
+        .write_all(&len.to_be_bytes())
+        .map_err(DatabaseError::Io)?;
+    stream.write_all(&payload).map_err(DatabaseError::Io)
+}
+
+// The receiving half of `write_framed`: read the length prefix, then exactly that many bytes,
+// then decode them as JSON.
+fn read_framed<T: DeserializeOwned>(stream: &mut TcpStream) -> Result<T, DatabaseError> {
+    let mut len_buf = [0u8; 4];
+    stream.read_exact(&mut len_buf).map_err(DatabaseError::Io)?;
+    let len = u32::from_be_bytes(len_buf) as usize;
+
+    let mut payload = vec![0u8; len];
+    stream.read_exact(&mut payload).map_err(DatabaseError::Io)?;
+    serde_json::from_slice(&payload).map_err(DatabaseError::Serde)
+}
+
+impl NetworkedDb {
+    fn roundtrip(&self, request: &Request) -> Result<Response, DatabaseError> {
+        let mut stream = TcpStream::connect_timeout(&self.store_addr, STORE_TIMEOUT)
+            .map_err(DatabaseError::Io)?;
+        stream
+            .set_read_timeout(Some(STORE_TIMEOUT))
+            .map_err(DatabaseError::Io)?;
+        stream
+            .set_write_timeout(Some(STORE_TIMEOUT))
+            .map_err(DatabaseError::Io)?;
+
+        write_framed(&mut stream, request)?;
+        read_framed(&mut stream)
+    }
+}
+
+impl Db for NetworkedDb {
+    fn signed_outpoint(&self, outpoint: &OutPoint) -> Result<Option<SignedOutpoint>, DatabaseError> {
+        let res = self.roundtrip(&Request::SignedOutpoint {
+            outpoint: *outpoint,
+        })?;
+        Ok(res.conflict)
+    }
+
+    fn try_record_spend(
+        &self,
+        spend_txid: &Txid,
+        signed_outpoints: &[(OutPoint, Vec<u8>)],
+    ) -> Result<Option<SignedOutpoint>, DatabaseError> {
+        let res = self.roundtrip(&Request::TryRecordSpend {
+            spend_txid: *spend_txid,
+            signed_outpoints: signed_outpoints.to_vec(),
+        })?;
+        Ok(res.conflict)
+    }
+}
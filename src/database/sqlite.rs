@@ -0,0 +1,179 @@
+//! The default `Db` backend: a local SQLite file.
+//!
+//! This is a single point of failure and doesn't survive the machine it runs on going down, but
+//! it's trivial to operate and is all a single cosigner instance needs.
+
+use crate::database::{DatabaseError, Db, SignedOutpoint};
+
+use revault_tx::miniscript::bitcoin::{consensus::encode, OutPoint, Txid};
+
+use std::path::{Path, PathBuf};
+
+use rusqlite::{params, Connection, ErrorCode, OptionalExtension};
+
+pub const DB_VERSION: i32 = 0;
+
+#[derive(Debug)]
+pub struct SqliteDb {
+    path: PathBuf,
+}
+
+impl SqliteDb {
+    /// Open (creating the schema if needed) the SQLite database at `path`.
+    pub fn new(path: PathBuf) -> Result<SqliteDb, DatabaseError> {
+        setup_db(&path)?;
+        Ok(SqliteDb { path })
+    }
+}
+
+/// Create the database file and its schema if it does not already exist.
+fn setup_db(db_path: &Path) -> Result<(), DatabaseError> {
+    let conn = Connection::open(db_path)?;
+
+    conn.execute_batch(
+        "
+        CREATE TABLE IF NOT EXISTS version (
+            version INTEGER NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS signed_outpoints (
+            id INTEGER PRIMARY KEY,
+            unvault_txid BLOB NOT NULL,
+            unvault_vout INTEGER NOT NULL,
+            spend_txid BLOB NOT NULL,
+            signature BLOB NOT NULL,
+            UNIQUE (unvault_txid, unvault_vout)
+        );
+        ",
+    )?;
+
+    let version_count: i32 =
+        conn.query_row("SELECT COUNT(*) FROM version", params![], |row| row.get(0))?;
+    if version_count == 0 {
+        conn.execute(
+            "INSERT INTO version (version) VALUES (?1)",
+            params![DB_VERSION],
+        )?;
+    }
+
+    Ok(())
+}
+
+fn row_to_signed_outpoint(spend_txid: Vec<u8>, signature: Vec<u8>) -> Result<SignedOutpoint, DatabaseError> {
+    Ok(SignedOutpoint {
+        spend_txid: encode::deserialize(&spend_txid).map_err(DatabaseError::Encode)?,
+        signature,
+    })
+}
+
+/// Whether `err` is the `UNIQUE(unvault_txid, unvault_vout)` constraint firing, as opposed to
+/// some other (e.g. I/O) failure.
+fn is_unique_violation(err: &rusqlite::Error) -> bool {
+    matches!(
+        err,
+        rusqlite::Error::SqliteFailure(e, _) if e.code == ErrorCode::ConstraintViolation
+    )
+}
+
+impl Db for SqliteDb {
+    fn signed_outpoint(&self, outpoint: &OutPoint) -> Result<Option<SignedOutpoint>, DatabaseError> {
+        let conn = Connection::open(&self.path)?;
+
+        let row = conn
+            .query_row(
+                "SELECT spend_txid, signature FROM signed_outpoints \
+                 WHERE unvault_txid = ?1 AND unvault_vout = ?2",
+                params![outpoint.txid.to_vec(), outpoint.vout],
+                |row| Ok((row.get::<_, Vec<u8>>(0)?, row.get::<_, Vec<u8>>(1)?)),
+            )
+            .optional()?;
+
+        row.map(|(spend_txid, signature)| row_to_signed_outpoint(spend_txid, signature))
+            .transpose()
+    }
+
+    fn try_record_spend(
+        &self,
+        spend_txid: &Txid,
+        signed_outpoints: &[(OutPoint, Vec<u8>)],
+    ) -> Result<Option<SignedOutpoint>, DatabaseError> {
+        let mut conn = Connection::open(&self.path)?;
+        let tx = conn.transaction()?;
+
+        // Track which outpoints are already recorded for *this* spend (a retry), so we don't
+        // try to re-insert them below: the `UNIQUE(unvault_txid, unvault_vout)` constraint would
+        // otherwise reject that as a conflict even though nothing actually changed.
+        let mut already_recorded = vec![false; signed_outpoints.len()];
+        for (i, (outpoint, _)) in signed_outpoints.iter().enumerate() {
+            let row = tx
+                .query_row(
+                    "SELECT spend_txid, signature FROM signed_outpoints \
+                     WHERE unvault_txid = ?1 AND unvault_vout = ?2",
+                    params![outpoint.txid.to_vec(), outpoint.vout],
+                    |row| Ok((row.get::<_, Vec<u8>>(0)?, row.get::<_, Vec<u8>>(1)?)),
+                )
+                .optional()?;
+
+            if let Some((prev_spend_txid, signature)) = row {
+                let prev = row_to_signed_outpoint(prev_spend_txid, signature)?;
+                if &prev.spend_txid != spend_txid {
+                    return Ok(Some(prev));
+                }
+                already_recorded[i] = true;
+            }
+        }
+
+        // A plain `INSERT`, not `INSERT OR IGNORE`: past this point every outpoint we still try
+        // to insert is new as far as we could tell, so a `UNIQUE(unvault_txid, unvault_vout)`
+        // violation here means another connection raced us between the check above and this
+        // write. That race can be two *different* spends genuinely fighting over the same
+        // outpoint, which must surface as a conflict rather than be silently swallowed, but it
+        // can just as well be two managers submitting the *same* spend concurrently, which must
+        // not be turned into a spurious refusal of an otherwise legitimate, idempotent retry.
+        for (i, (outpoint, signature)) in signed_outpoints.iter().enumerate() {
+            if already_recorded[i] {
+                continue;
+            }
+            let insert_res = tx.execute(
+                "INSERT INTO signed_outpoints \
+                 (unvault_txid, unvault_vout, spend_txid, signature) VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    outpoint.txid.to_vec(),
+                    outpoint.vout,
+                    spend_txid.to_vec(),
+                    signature,
+                ],
+            );
+
+            if let Err(e) = insert_res {
+                if !is_unique_violation(&e) {
+                    return Err(e.into());
+                }
+
+                let row = tx
+                    .query_row(
+                        "SELECT spend_txid, signature FROM signed_outpoints \
+                         WHERE unvault_txid = ?1 AND unvault_vout = ?2",
+                        params![outpoint.txid.to_vec(), outpoint.vout],
+                        |row| Ok((row.get::<_, Vec<u8>>(0)?, row.get::<_, Vec<u8>>(1)?)),
+                    )
+                    .optional()?;
+                let (prev_spend_txid, signature) = match row {
+                    Some(row) => row,
+                    // The constraint firing means a row is there; if we can't see it within our
+                    // own transaction something is badly wrong, so surface the original error.
+                    None => return Err(e.into()),
+                };
+                let prev = row_to_signed_outpoint(prev_spend_txid, signature)?;
+                if &prev.spend_txid != spend_txid {
+                    return Ok(Some(prev));
+                }
+                // Another connection recorded this very spend for this outpoint between our
+                // check and our insert: nothing left to do for it.
+            }
+        }
+
+        tx.commit()?;
+        Ok(None)
+    }
+}
@@ -0,0 +1,8 @@
+pub mod config;
+pub mod cosignerd;
+pub mod database;
+pub mod pool;
+pub mod signer;
+
+#[cfg(test)]
+mod tests;
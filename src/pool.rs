@@ -0,0 +1,73 @@
+//! A minimal fixed-size thread pool used to bound how many manager connections we process at
+//! once: a single slow or malicious manager blocking one worker no longer denies service to the
+//! others, while `size` caps how many connections we ever handle concurrently.
+
+use std::{
+    sync::{mpsc, Arc, Mutex},
+    thread,
+};
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+pub struct ThreadPool {
+    #[allow(dead_code)]
+    workers: Vec<Worker>,
+    sender: mpsc::SyncSender<Job>,
+}
+
+impl ThreadPool {
+    /// Spawn a pool of `size` worker threads. Panics if `size` is 0.
+    ///
+    /// The job queue is bounded to `size` pending connections on top of the ones already being
+    /// worked on: a connection flood applies backpressure on `execute`'s caller (the accept loop)
+    /// instead of queuing without limit and growing memory unboundedly.
+    pub fn new(size: usize) -> ThreadPool {
+        assert!(size > 0, "Thread pool size must be at least 1");
+
+        let (sender, receiver) = mpsc::sync_channel(size);
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let workers = (0..size)
+            .map(|id| Worker::new(id, Arc::clone(&receiver)))
+            .collect();
+
+        ThreadPool { workers, sender }
+    }
+
+    /// Queue a job for the next available worker. Blocks once the backlog of `size` pending jobs
+    /// is full, so the accept loop itself stalls (rather than our memory usage growing) under a
+    /// connection flood.
+    pub fn execute<F>(&self, job: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.sender
+            .send(Box::new(job))
+            .expect("Worker threads outlive the pool");
+    }
+}
+
+struct Worker {
+    #[allow(dead_code)]
+    id: usize,
+    #[allow(dead_code)]
+    thread: thread::JoinHandle<()>,
+}
+
+impl Worker {
+    fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Job>>>) -> Worker {
+        let thread = thread::spawn(move || loop {
+            let job = receiver
+                .lock()
+                .expect("Worker pool's queue lock is poisoned")
+                .recv();
+
+            match job {
+                Ok(job) => job(),
+                Err(_) => break,
+            }
+        });
+
+        Worker { id, thread }
+    }
+}
@@ -0,0 +1,91 @@
+//! Abstraction over the Bitcoin signing key.
+//!
+//! We never want the daemon's core logic to assume the private key lives in its own process
+//! memory: an operator may want to delegate signing to a networked HSM or an air-gapped device
+//! instead. The `Signer` trait is the boundary between "knows how to produce a signature for a
+//! given derivation index" and "processes Sign messages", mirroring how rust-lightning keeps
+//! `SignerProvider` as the sole interface to key material.
+
+use revault_tx::miniscript::bitcoin::{
+    secp256k1::{self, rand::rngs::OsRng},
+    util::bip32::{ChildNumber, ExtendedPrivKey, ExtendedPubKey},
+};
+
+use std::{fmt, fs, io, path::Path};
+
+/// Something that can produce signatures for the cosigner's Bitcoin key, without necessarily
+/// exposing the private key material itself.
+pub trait Signer: fmt::Debug + Send + Sync {
+    /// Sign a sighash with the key derived at `derivation`, the index of the vault being spent.
+    fn sign_spend_input(
+        &self,
+        sighash: &secp256k1::Message,
+        derivation: ChildNumber,
+    ) -> secp256k1::Signature;
+
+    /// The master extended public key of this signer. Per-vault public keys are derived from it
+    /// at the same index used to sign, so callers can recover the key a signature was made
+    /// against without ever seeing the private key material.
+    fn xpub(&self) -> ExtendedPubKey;
+}
+
+/// The default, in-process `Signer`: a plain BIP32 extended key held in the daemon's memory.
+///
+/// This is what cosignerd has always done. It remains the default backend, but operators who
+/// want their key to never touch the daemon's process can implement `Signer` against a
+/// networked or air-gapped signer instead.
+#[derive(Debug)]
+pub struct InProcessSigner {
+    xpriv: ExtendedPrivKey,
+    xpub: ExtendedPubKey,
+}
+
+impl InProcessSigner {
+    pub fn new(xpriv: ExtendedPrivKey) -> Self {
+        let secp = secp256k1::Secp256k1::new();
+        let xpub = ExtendedPubKey::from_private(&secp, &xpriv);
+        InProcessSigner { xpriv, xpub }
+    }
+
+    /// Read our master extended private key from `path`, generating and persisting a new one if
+    /// it does not exist yet.
+    pub fn read_or_create(path: &Path) -> Result<Self, io::Error> {
+        use revault_tx::miniscript::bitcoin::Network;
+
+        let xpriv = if path.exists() {
+            let bytes = fs::read(path)?;
+            ExtendedPrivKey::decode(&bytes).expect("Invalid xpriv stored on disk")
+        } else {
+            let mut seed = [0u8; 32];
+            secp256k1::rand::RngCore::fill_bytes(
+                &mut OsRng::new().expect("Getting OS RNG"),
+                &mut seed,
+            );
+            let xpriv = ExtendedPrivKey::new_master(Network::Bitcoin, &seed)
+                .expect("Computing a master key from a 32 bytes seed can't fail");
+            fs::write(path, &xpriv.encode())?;
+            xpriv
+        };
+
+        Ok(InProcessSigner::new(xpriv))
+    }
+}
+
+impl Signer for InProcessSigner {
+    fn sign_spend_input(
+        &self,
+        sighash: &secp256k1::Message,
+        derivation: ChildNumber,
+    ) -> secp256k1::Signature {
+        let secp = secp256k1::Secp256k1::new();
+        let child_xpriv = self
+            .xpriv
+            .derive_priv(&secp, &[derivation])
+            .expect("Deriving a single-depth child of a valid xpriv can't fail");
+        secp.sign(sighash, &child_xpriv.private_key.key)
+    }
+
+    fn xpub(&self) -> ExtendedPubKey {
+        self.xpub
+    }
+}
@@ -1,4 +1,7 @@
-use crate::{config::Config, config::ManagerConfig, database::setup_db};
+use crate::{
+    config::{Config, DbConfig, ManagerConfig, NoiseKeyEntry, SignerConfig},
+    signer::{InProcessSigner, Signer},
+};
 use revault_net::{noise::SecretKey as NoisePrivkey, sodiumoxide};
 use revault_tx::{
     miniscript::{
@@ -8,9 +11,7 @@ use revault_tx::{
             util::bip32::{self, ChildNumber},
             Network, OutPoint, TxOut,
         },
-        descriptor::{
-            DescriptorPublicKey, DescriptorPublicKeyCtx, DescriptorSinglePub, DescriptorXKey,
-        },
+        descriptor::{DescriptorPublicKey, DescriptorPublicKeyCtx, DescriptorXKey},
     },
     scripts::{cpfp_descriptor, unvault_descriptor},
     transactions::SpendTransaction,
@@ -35,7 +36,7 @@ fn random_privkey(rng: &mut SmallRng) -> bip32::ExtendedPrivKey {
 pub struct CosignerTestBuilder {
     pub config: Config,
     pub noise_privkey: NoisePrivkey,
-    pub bitcoin_privkey: secp256k1::SecretKey,
+    pub signer: InProcessSigner,
     pub managers_keys: Vec<DescriptorPublicKey>,
 }
 
@@ -56,7 +57,12 @@ impl CosignerTestBuilder {
             managers_keys.push(xpub);
 
             let noise_key = sodiumoxide::crypto::box_::gen_keypair().0;
-            managers.push(ManagerConfig { noise_key });
+            managers.push(ManagerConfig {
+                noise_keys: vec![NoiseKeyEntry {
+                    key: noise_key,
+                    deprecated: false,
+                }],
+            });
         }
 
         // Use a scratch directory in /tmp
@@ -78,37 +84,49 @@ impl CosignerTestBuilder {
         let data_dir = PathBuf::from_str(&data_dir_str).unwrap();
         let listen = SocketAddr::from_str("127.0.0.1:8383").unwrap();
 
-        let mut db_path = data_dir.clone();
-        db_path.push("cosignerd.sqlite3");
-        setup_db(&db_path).expect("Setting up db");
-
         let config = Config {
             managers,
             data_dir,
             listen,
             log_level: log::LevelFilter::Trace,
             daemon: false,
+            signer: SignerConfig::InProcess,
+            max_connections: 8,
+            db: DbConfig::Sqlite,
         };
 
         let noise_privkey = sodiumoxide::crypto::box_::gen_keypair().1;
-        let bitcoin_privkey = secp256k1::SecretKey::new(&mut rng);
+        let signer = InProcessSigner::new(random_privkey(&mut rng));
 
         CosignerTestBuilder {
             config,
             noise_privkey,
-            bitcoin_privkey,
+            signer,
             managers_keys,
         }
     }
 
-    pub fn generate_spend_tx(&self, outpoints: &[OutPoint]) -> SpendTransaction {
+    /// Build a `SpendTransaction` spending `outpoints`, all belonging to vaults derived at
+    /// `derivation_index`, so that the signatures produced for it should validate against our own
+    /// signer's key derived at that same index.
+    pub fn generate_spend_tx(
+        &self,
+        outpoints: &[OutPoint],
+        derivation_index: ChildNumber,
+    ) -> SpendTransaction {
         let mut rng = SmallRng::from_entropy();
         let secp = secp256k1::Secp256k1::new();
-        let xpub_ctx = DescriptorPublicKeyCtx::new(&secp, ChildNumber::from(0));
+        let xpub_ctx = DescriptorPublicKeyCtx::new(&secp, derivation_index);
         let unvault_value: u64 = 100000000;
         let n_stk = 10;
         let csv = 12;
 
+        let our_cosigner_key = DescriptorPublicKey::XPub(DescriptorXKey {
+            origin: None,
+            xkey: self.signer.xpub(),
+            derivation_path: bip32::DerivationPath::from(vec![]),
+            is_wildcard: true,
+        });
         let mut stakeholders_keys = Vec::with_capacity(n_stk);
         let mut cosigners_keys = Vec::with_capacity(n_stk);
         for _ in 0..n_stk {
@@ -118,11 +136,7 @@ impl CosignerTestBuilder {
                 derivation_path: bip32::DerivationPath::from(vec![]),
                 is_wildcard: true,
             }));
-            cosigners_keys.push(DescriptorPublicKey::SinglePub(DescriptorSinglePub {
-                origin: None,
-                key: bip32::ExtendedPubKey::from_private(&secp, &random_privkey(&mut rng))
-                    .public_key,
-            }));
+            cosigners_keys.push(our_cosigner_key.clone());
         }
         let unvault_descriptor = unvault_descriptor(
             stakeholders_keys,
@@ -178,19 +192,39 @@ mod tests {
     #[test]
     fn test_builder() {
         let test_framework = CosignerTestBuilder::new(5);
-        test_framework.generate_spend_tx(&[
-            OutPoint::from_str(
-                "2b8930127e9dfd1bcdf35df2bc7f3b8cdbec083b1ae693f36b6305fccd1425da:0",
-            )
-            .unwrap(),
-            OutPoint::from_str(
-                "ceca4de398c63b29543f8346c09fd7522fd8661ce8bdc0e454e8d6ed8ad46a0d:1",
-            )
-            .unwrap(),
-            OutPoint::from_str(
-                "0b38682347207cd79de33edf8897a75abe7d8799b194439150306773b6aef55a:189",
-            )
-            .unwrap(),
-        ]);
+        let derivation_index = ChildNumber::from(42);
+        let spend_tx = test_framework.generate_spend_tx(
+            &[
+                OutPoint::from_str(
+                    "2b8930127e9dfd1bcdf35df2bc7f3b8cdbec083b1ae693f36b6305fccd1425da:0",
+                )
+                .unwrap(),
+                OutPoint::from_str(
+                    "ceca4de398c63b29543f8346c09fd7522fd8661ce8bdc0e454e8d6ed8ad46a0d:1",
+                )
+                .unwrap(),
+                OutPoint::from_str(
+                    "0b38682347207cd79de33edf8897a75abe7d8799b194439150306773b6aef55a:189",
+                )
+                .unwrap(),
+            ],
+            derivation_index,
+        );
+
+        // A signature produced for the vault's derivation index must validate against our
+        // signer's key derived at that same index, not at any other one.
+        let secp = secp256k1::Secp256k1::new();
+        let sighash = spend_tx.signature_hash(0, &secp);
+        let signature = test_framework
+            .signer
+            .sign_spend_input(&sighash, derivation_index);
+        let child_pubkey = test_framework
+            .signer
+            .xpub()
+            .derive_pub(&secp, &[derivation_index])
+            .unwrap()
+            .public_key;
+        secp.verify(&sighash, &signature, &child_pubkey.key)
+            .expect("Signature must validate against the descriptor-derived child pubkey");
     }
 }
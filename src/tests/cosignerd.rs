@@ -0,0 +1,162 @@
+use crate::{
+    cosignerd::CosignerD,
+    database::{sqlite::SqliteDb, Db},
+    signer::Signer,
+    tests::builder::CosignerTestBuilder,
+};
+
+use revault_tx::miniscript::bitcoin::{secp256k1, util::bip32::ChildNumber, OutPoint};
+
+use std::str::FromStr;
+
+fn new_cosignerd(n_man: usize) -> (CosignerTestBuilder, CosignerD) {
+    let builder = CosignerTestBuilder::new(n_man);
+    let cosignerd =
+        CosignerD::from_config(builder.config.clone()).expect("Building CosignerD from config");
+    (builder, cosignerd)
+}
+
+#[test]
+fn process_sign_message_refuses_a_conflicting_spend() {
+    let (builder, cosignerd) = new_cosignerd(3);
+    let derivation_index = ChildNumber::from(0);
+    let outpoint =
+        OutPoint::from_str("2b8930127e9dfd1bcdf35df2bc7f3b8cdbec083b1ae693f36b6305fccd1425da:0")
+            .unwrap();
+
+    let first_spend = builder.generate_spend_tx(&[outpoint], derivation_index);
+    let res = cosignerd
+        .process_sign_message(first_spend.clone())
+        .expect("Processing the first spend");
+    assert!(
+        res.tx.is_some(),
+        "The first spend of a fresh outpoint must be signed"
+    );
+
+    // A distinct spend transaction, but it still tries to spend the outpoint we just signed for.
+    let conflicting_spend = builder.generate_spend_tx(&[outpoint], derivation_index);
+    assert_ne!(first_spend.txid(), conflicting_spend.txid());
+    let res = cosignerd
+        .process_sign_message(conflicting_spend)
+        .expect("Processing the conflicting spend");
+    assert!(
+        res.tx.is_none(),
+        "A distinct spend of an already-signed outpoint must be refused"
+    );
+}
+
+#[test]
+fn process_sign_message_is_idempotent_for_a_retried_spend() {
+    let (builder, cosignerd) = new_cosignerd(3);
+    let derivation_index = ChildNumber::from(1);
+    let outpoint =
+        OutPoint::from_str("8dde8798c6daeb20a969b3f817297dd8cef4ffbf05013222be3af799f9c7c90d:1")
+            .unwrap();
+    let spend_tx = builder.generate_spend_tx(&[outpoint], derivation_index);
+
+    let first_res = cosignerd
+        .process_sign_message(spend_tx.clone())
+        .expect("Processing the spend a first time");
+    assert!(first_res.tx.is_some());
+
+    // A manager retrying the very same spend (e.g. after a dropped connection) must not be
+    // refused as if it were a conflicting spend.
+    let second_res = cosignerd
+        .process_sign_message(spend_tx)
+        .expect("Processing the same spend again");
+    assert!(
+        second_res.tx.is_some(),
+        "Retrying the same spend must succeed again"
+    );
+}
+
+#[test]
+fn process_sign_message_signs_disjoint_spends() {
+    let (builder, cosignerd) = new_cosignerd(3);
+    let derivation_index = ChildNumber::from(2);
+    let outpoint_a =
+        OutPoint::from_str("1d1eac6b428dc71ffe235c4bc2133387cac19a464ad089701e648b57fadb27f2:0")
+            .unwrap();
+    let outpoint_b =
+        OutPoint::from_str("a9149f4f46d0ea818bb4e18677610113af27642a6871b7a1c62c97bc32cad45f:3")
+            .unwrap();
+
+    let spend_a = builder.generate_spend_tx(&[outpoint_a], derivation_index);
+    let spend_b = builder.generate_spend_tx(&[outpoint_b], derivation_index);
+
+    let res_a = cosignerd
+        .process_sign_message(spend_a)
+        .expect("Processing the first, disjoint spend");
+    let res_b = cosignerd
+        .process_sign_message(spend_b)
+        .expect("Processing the second, disjoint spend");
+    assert!(res_a.tx.is_some());
+    assert!(res_b.tx.is_some());
+}
+
+#[test]
+fn process_sign_message_signs_with_each_vault_own_derivation_index() {
+    let (builder, cosignerd) = new_cosignerd(3);
+    // Two vaults derived at distinct, non-zero indices: if the signing path ever fell back to a
+    // fixed or default index, the signature for at least one of them would fail to validate
+    // against its own vault's derived key.
+    let index_a = ChildNumber::from(7);
+    let index_b = ChildNumber::from(42);
+    let outpoint_a =
+        OutPoint::from_str("c90f89f9eb4d5e9c1b5ae9ab7a2a9d62b4e6dc2f4d1c6d2f6d2f6d2f6d2f6d2f:0")
+            .unwrap();
+    let outpoint_b =
+        OutPoint::from_str("3f4c5d6e7a8b9c0d1e2f3a4b5c6d7e8f9a0b1c2d3e4f5a6b7c8d9e0f1a2b3c4d:1")
+            .unwrap();
+
+    let spend_a = builder.generate_spend_tx(&[outpoint_a], index_a);
+    let spend_b = builder.generate_spend_tx(&[outpoint_b], index_b);
+    let sighash_a = spend_a.signature_hash(0, &secp256k1::Secp256k1::new());
+    let sighash_b = spend_b.signature_hash(0, &secp256k1::Secp256k1::new());
+
+    assert!(cosignerd.process_sign_message(spend_a).unwrap().tx.is_some());
+    assert!(cosignerd.process_sign_message(spend_b).unwrap().tx.is_some());
+
+    // Read the persisted signatures back independently and check each validates against the
+    // signer's key derived at *its own* vault's index, not the other one's.
+    let db = SqliteDb::new(builder.config.data_dir.join("cosignerd.sqlite3"))
+        .expect("Opening the cosigner's sqlite db");
+    let secp = secp256k1::Secp256k1::new();
+
+    let sig_a = db
+        .signed_outpoint(&outpoint_a)
+        .unwrap()
+        .expect("Outpoint A was recorded")
+        .signature;
+    let sig_b = db
+        .signed_outpoint(&outpoint_b)
+        .unwrap()
+        .expect("Outpoint B was recorded")
+        .signature;
+
+    let pubkey_a = cosignerd
+        .signer
+        .xpub()
+        .derive_pub(&secp, &[index_a])
+        .unwrap()
+        .public_key;
+    let pubkey_b = cosignerd
+        .signer
+        .xpub()
+        .derive_pub(&secp, &[index_b])
+        .unwrap()
+        .public_key;
+
+    secp.verify(
+        &sighash_a,
+        &secp256k1::Signature::from_der(&sig_a).unwrap(),
+        &pubkey_a.key,
+    )
+    .expect("Vault A's signature must validate against its own derivation index");
+    secp.verify(
+        &sighash_b,
+        &secp256k1::Signature::from_der(&sig_b).unwrap(),
+        &pubkey_b.key,
+    )
+    .expect("Vault B's signature must validate against its own derivation index");
+}